@@ -0,0 +1,244 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use libc::size_t;
+use cl_h::{self, cl_context, cl_device_id, cl_program};
+
+/// Configuration for building an OpenCL program, passed to `ProQue::build()`.
+///
+/// Collects kernel source (via `kern_embed()`) and compiler options, and optionally
+/// a directory to cache compiled program binaries in (via `binary_cache()`) so that
+/// later runs with unchanged source and options can skip source compilation entirely.
+pub struct BuildConfig {
+	kernel_srcs: Vec<String>,
+	cmplr_opts: String,
+	binary_cache_dir: Option<PathBuf>,
+}
+
+impl BuildConfig {
+	/// Returns a new, empty `BuildConfig`.
+	pub fn new() -> BuildConfig {
+		BuildConfig {
+			kernel_srcs: Vec::new(),
+			cmplr_opts: String::new(),
+			binary_cache_dir: None,
+		}
+	}
+
+	/// Embeds `src` as kernel source to build. May be called more than once to build
+	/// a program from multiple source strings.
+	pub fn kern_embed(mut self, src: &str) -> BuildConfig {
+		self.kernel_srcs.push(src.to_string());
+		self
+	}
+
+	/// Sets the extra options string passed to `clBuildProgram` (e.g. `"-D FOO=1"`).
+	pub fn cmplr_opts(mut self, opts: &str) -> BuildConfig {
+		self.cmplr_opts = opts.to_string();
+		self
+	}
+
+	/// Enables binary caching under `dir`: after a successful source build, the
+	/// compiled-and-linked program binary for each device is saved to `dir`, keyed
+	/// by a stable device identity string and a hash of the source and compiler
+	/// options. On a later `build()` with unchanged source/options/device, a valid
+	/// cached binary is loaded via `clCreateProgramWithBinary` instead of recompiling
+	/// from source.
+	pub fn binary_cache<P: AsRef<Path>>(mut self, dir: P) -> BuildConfig {
+		self.binary_cache_dir = Some(dir.as_ref().to_path_buf());
+		self
+	}
+
+	/// Builds a `cl_program` for `device_id` within `context`, using a cached binary
+	/// in place of `kernel_srcs` if `binary_cache()` was set and a valid one exists.
+	///
+	/// A missing, stale, or rejected cache entry is treated as a harmless cache miss:
+	/// `build()` falls back to compiling `kernel_srcs` from scratch and (if caching is
+	/// enabled) re-saves the freshly built binary over the bad entry.
+	pub fn build(&self, context: cl_context, device_id: cl_device_id) -> Result<cl_program, String> {
+		if let Some(ref cache_dir) = self.binary_cache_dir {
+			let cache_path = self.cache_path(cache_dir, device_id);
+
+			if let Some(program) = self.try_load_cached(context, device_id, &cache_path) {
+				return Ok(program);
+			}
+		}
+
+		let program = try!(self.build_from_source(context, device_id));
+
+		if let Some(ref cache_dir) = self.binary_cache_dir {
+			let cache_path = self.cache_path(cache_dir, device_id);
+			// Caching is a best-effort optimization; failing to write it shouldn't
+			// fail the build.
+			let _ = self.save_to_cache(program, device_id, &cache_path);
+		}
+
+		Ok(program)
+	}
+
+	/// The cache file path for `device_id`, namespaced by a hash of the source,
+	/// compiler options, and a stable device identity string (name/vendor/version --
+	/// *not* the driver-assigned `cl_device_id` pointer, which isn't guaranteed to be
+	/// the same across process invocations) so stale binaries are never loaded after
+	/// any of those change.
+	fn cache_path(&self, cache_dir: &Path, device_id: cl_device_id) -> PathBuf {
+		let mut hasher = DefaultHasher::new();
+		self.kernel_srcs.hash(&mut hasher);
+		self.cmplr_opts.hash(&mut hasher);
+		device_identity_string(device_id).hash(&mut hasher);
+		let cache_key = hasher.finish();
+
+		cache_dir.join(format!("{:016x}.bin", cache_key))
+	}
+
+	/// Attempts to load and build a cached binary for `device_id`. Returns `None` on
+	/// any failure along the way (no cache file, a binary the driver rejects, or a
+	/// failed `clBuildProgram`) -- the caller should treat that as a cache miss and
+	/// fall back to `build_from_source`. Any `cl_program` created during a failed
+	/// attempt is released before returning so it isn't leaked.
+	fn try_load_cached(&self, context: cl_context, device_id: cl_device_id, cache_path: &Path)
+			-> Option<cl_program>
+	{
+		let mut binary = Vec::new();
+
+		match File::open(cache_path) {
+			Ok(mut file) => {
+				if file.read_to_end(&mut binary).is_err() { return None; }
+			},
+			Err(_) => return None,
+		}
+
+		let mut errcode = 0i32;
+		let mut binary_status = 0i32;
+		let binary_len = binary.len() as size_t;
+		let binary_ptr = binary.as_ptr();
+
+		let program = unsafe { cl_h::clCreateProgramWithBinary(
+			context, 1, &device_id, &binary_len, &binary_ptr, &mut binary_status, &mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 || binary_status != cl_h::CL_SUCCESS as i32 {
+			if errcode == cl_h::CL_SUCCESS as i32 { unsafe { cl_h::clReleaseProgram(program); } }
+			return None;
+		}
+
+		let opts = ::std::ffi::CString::new(self.cmplr_opts.clone()).unwrap_or_default();
+
+		let errcode = unsafe { cl_h::clBuildProgram(
+			program, 1, &device_id, opts.as_ptr(), None, ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			unsafe { cl_h::clReleaseProgram(program); }
+			return None;
+		}
+
+		Some(program)
+	}
+
+	fn build_from_source(&self, context: cl_context, device_id: cl_device_id) -> Result<cl_program, String> {
+		if self.kernel_srcs.is_empty() {
+			return Err("BuildConfig::build(): No kernel source specified. \
+				Call `.kern_embed()` before `.build()`.".to_string());
+		}
+
+		let src_ptrs: Vec<*const i8> = self.kernel_srcs.iter().map(|s| s.as_ptr() as *const i8).collect();
+		let src_lens: Vec<size_t> = self.kernel_srcs.iter().map(|s| s.len() as size_t).collect();
+
+		let mut errcode = 0i32;
+
+		let program = unsafe { cl_h::clCreateProgramWithSource(
+			context, src_ptrs.len() as u32, src_ptrs.as_ptr(), src_lens.as_ptr(), &mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err(format!("BuildConfig::build(): `clCreateProgramWithSource` failed (errcode: {})", errcode));
+		}
+
+		let opts = ::std::ffi::CString::new(self.cmplr_opts.clone()).unwrap_or_default();
+
+		let errcode = unsafe { cl_h::clBuildProgram(
+			program, 1, &device_id, opts.as_ptr(), None, ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			unsafe { cl_h::clReleaseProgram(program); }
+			return Err(format!("BuildConfig::build(): `clBuildProgram` failed (errcode: {})", errcode));
+		}
+
+		Ok(program)
+	}
+
+	/// Queries the compiled binary for `device_id` out of `program` via
+	/// `CL_PROGRAM_BINARY_SIZES`/`CL_PROGRAM_BINARIES` and saves it to `cache_path`.
+	fn save_to_cache(&self, program: cl_program, device_id: cl_device_id, cache_path: &Path) -> Result<(), String> {
+		if let Some(parent) = cache_path.parent() {
+			try!(fs::create_dir_all(parent).map_err(|e| e.to_string()));
+		}
+
+		let device_ids = unsafe { self.program_devices(program) };
+		let device_idx = match device_ids.iter().position(|&d| d == device_id) {
+			Some(idx) => idx,
+			None => return Err("BuildConfig: device not found in program's device list".to_string()),
+		};
+
+		let mut binary_sizes: Vec<size_t> = vec![0; device_ids.len()];
+
+		unsafe { cl_h::clGetProgramInfo(
+			program, cl_h::CL_PROGRAM_BINARY_SIZES,
+			(binary_sizes.len() * ::std::mem::size_of::<size_t>()) as size_t,
+			binary_sizes.as_mut_ptr() as *mut _, ptr::null_mut(),
+		) };
+
+		let mut binaries: Vec<Vec<u8>> = binary_sizes.iter().map(|&size| vec![0u8; size as usize]).collect();
+		let mut binary_ptrs: Vec<*mut u8> = binaries.iter_mut().map(|b| b.as_mut_ptr()).collect();
+
+		unsafe { cl_h::clGetProgramInfo(
+			program, cl_h::CL_PROGRAM_BINARIES,
+			(binary_ptrs.len() * ::std::mem::size_of::<*mut u8>()) as size_t,
+			binary_ptrs.as_mut_ptr() as *mut _, ptr::null_mut(),
+		) };
+
+		let mut file = try!(File::create(cache_path).map_err(|e| e.to_string()));
+		file.write_all(&binaries[device_idx]).map_err(|e| e.to_string())
+	}
+
+	unsafe fn program_devices(&self, program: cl_program) -> Vec<cl_device_id> {
+		let mut num_devices: cl_h::cl_uint = 0;
+		cl_h::clGetProgramInfo(program, cl_h::CL_PROGRAM_NUM_DEVICES,
+			::std::mem::size_of::<cl_h::cl_uint>() as size_t,
+			&mut num_devices as *mut _ as *mut _, ptr::null_mut());
+
+		let mut device_ids: Vec<cl_device_id> = vec![ptr::null_mut(); num_devices as usize];
+		cl_h::clGetProgramInfo(program, cl_h::CL_PROGRAM_DEVICES,
+			(device_ids.len() * ::std::mem::size_of::<cl_device_id>()) as size_t,
+			device_ids.as_mut_ptr() as *mut _, ptr::null_mut());
+
+		device_ids
+	}
+}
+
+/// A stable identity string for `device_id` (name + vendor + driver version), suitable
+/// for use as a cache key across process invocations. Unlike the raw `cl_device_id`
+/// pointer, these stay the same across runs for a given device and driver install; a
+/// driver update changes the version and so the cache key.
+fn device_identity_string(device_id: cl_device_id) -> String {
+	let name = device_info_string(device_id, cl_h::CL_DEVICE_NAME);
+	let vendor = device_info_string(device_id, cl_h::CL_DEVICE_VENDOR);
+	let driver_version = device_info_string(device_id, cl_h::CL_DRIVER_VERSION);
+
+	format!("{}|{}|{}", name, vendor, driver_version)
+}
+
+/// A failed query just degrades the cache key's uniqueness (worst case: a spurious
+/// cache miss), so it's fine to fall back to an empty string here rather than thread
+/// a `Result` through `cache_path()` -- unlike `Device::info()`, nothing treats this
+/// value as a real driver-reported number.
+fn device_info_string(device_id: cl_device_id, param_name: cl_h::cl_device_info) -> String {
+	super::cl_info::query_string(|size, value, size_ret| unsafe {
+		cl_h::clGetDeviceInfo(device_id, param_name, size, value, size_ret)
+	}).unwrap_or_default()
+}
@@ -0,0 +1,42 @@
+use std::os::raw::c_void;
+use libc::size_t;
+use cl_h::{self, cl_int};
+
+/// Runs the standard OpenCL "query twice" idiom for a variable-length string info
+/// query (`clGetDeviceInfo`/`clGetPlatformInfo`/etc.): once with a null buffer to
+/// learn the required size, then again into a buffer of that size.
+///
+/// `query` is called as `query(param_value_size, param_value, param_value_size_ret)`,
+/// mirroring the tail of the corresponding `clGet*Info` call. Returns the `cl_int`
+/// error code from whichever call first failed.
+pub fn query_string<F>(mut query: F) -> Result<String, cl_int>
+	where F: FnMut(size_t, *mut c_void, *mut size_t) -> cl_int
+{
+	let mut size: size_t = 0;
+
+	let errcode = query(0, ::std::ptr::null_mut(), &mut size);
+	if errcode != cl_h::CL_SUCCESS as cl_int { return Err(errcode); }
+
+	let mut buffer: Vec<u8> = vec![0u8; size as usize];
+
+	let errcode = query(size, buffer.as_mut_ptr() as *mut _, ::std::ptr::null_mut());
+	if errcode != cl_h::CL_SUCCESS as cl_int { return Err(errcode); }
+
+	if buffer.last() == Some(&0) { buffer.pop(); }
+
+	Ok(String::from_utf8(buffer).unwrap_or_default())
+}
+
+/// Runs a fixed-size info query (`clGetDeviceInfo`/`clGetPlatformInfo`/etc. for a
+/// numeric `param_name`) and returns the error code on failure instead of the
+/// default-initialized (and indistinguishable-from-a-real-zero) value.
+pub fn query_fixed<T, F>(mut query: F) -> Result<T, cl_int>
+	where T: Default, F: FnMut(size_t, *mut c_void) -> cl_int
+{
+	let mut value: T = T::default();
+
+	let errcode = query(::std::mem::size_of::<T>() as size_t, &mut value as *mut T as *mut _);
+	if errcode != cl_h::CL_SUCCESS as cl_int { return Err(errcode); }
+
+	Ok(value)
+}
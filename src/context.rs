@@ -1,6 +1,21 @@
 
 // use formatting::MT;
-use cl_h::{self, cl_platform_id, cl_device_id, cl_device_type, cl_context};
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::ptr;
+use libc::{size_t, c_char};
+use cl_h::{self, cl_platform_id, cl_device_id, cl_device_type, cl_context, cl_context_properties, cl_int};
+
+/// A boxed closure invoked by the OpenCL runtime when an asynchronous context
+/// error occurs (see `clCreateContext`'s `pfn_notify` parameter).
+///
+/// Receives the driver-supplied error string along with the raw private-info
+/// blob (pointer and length) exactly as handed to `pfn_notify`.
+///
+/// Must be `Send`: the OpenCL driver is free to invoke `pfn_notify` from its own
+/// internal thread, so a callback stashed here can be called from a thread other
+/// than the one that created the `Context`.
+pub type ContextErrorCallback = Box<FnMut(&str, &[u8]) + Send>;
 
 /// An OpenCL context for a particular platform and set of device types.
 ///
@@ -9,6 +24,37 @@ pub struct Context {
 	platform_opt: Option<cl_platform_id>,
 	device_ids: Vec<cl_device_id>,
 	obj: cl_context,
+	// Kept alive for the lifetime of `obj` so the trampoline below can
+	// safely dereference it on every async error callback.
+	_error_callback: Option<Box<ContextErrorCallback>>,
+	// Sub-devices (from `Device::partition()`) that this `Context` owns and must
+	// `clReleaseDevice` on `release()`. Normally a subset of `device_ids`, though
+	// `ContextBuilder::devices()` replaces the device list without dropping these.
+	owned_sub_devices: Vec<cl_device_id>,
+}
+
+/// Trampoline passed to `clCreateContext` as `pfn_notify`. `user_data` is a
+/// raw pointer to the boxed `ContextErrorCallback` stashed on the `Context`.
+extern "C" fn context_error_notify(errinfo: *const c_char, private_info: *const c_void,
+			cb: size_t, user_data: *mut c_void)
+{
+	if user_data.is_null() { return; }
+
+	let callback = unsafe { &mut *(user_data as *mut ContextErrorCallback) };
+
+	let errinfo = if errinfo.is_null() {
+		""
+	} else {
+		unsafe { CStr::from_ptr(errinfo) }.to_str().unwrap_or("")
+	};
+
+	let private_info = if private_info.is_null() || cb == 0 {
+		&[][..]
+	} else {
+		unsafe { ::std::slice::from_raw_parts(private_info as *const u8, cb as usize) }
+	};
+
+	callback(errinfo, private_info);
 }
 
 impl Context {
@@ -68,38 +114,70 @@ impl Context {
 	/// # TODO:
 	/// - Add a more in-depth constructor which accepts an arbitrary list of devices (or sub-devices) and a list of cl_context_properties.
 	///
-	/// # Maybe Someday TODO:
-	/// - Handle context callbacks.
-	///
-	pub fn new(platform_idx_opt: Option<usize>, device_types_opt: Option<cl_device_type>) 
+	pub fn new(platform_idx_opt: Option<usize>, device_types_opt: Option<cl_device_type>)
 			-> Result<Context, &'static str>
 	{
-		let platforms = super::get_platform_ids();
-		if platforms.len() == 0 { return Err("\nNo OpenCL platforms found!\n"); }
-
-		let platform = match platform_idx_opt {
-			Some(pf_idx) => {
-				match platforms.get(pf_idx) {
-					Some(&pf) => pf,
-					None => return Err("Invalid OpenCL platform index specified. \
-						Use 'get_platform_ids()' for a list."),
-				}				
-			},
+		Self::new_with_callback(platform_idx_opt, device_types_opt, None)
+	}
 
-			None => platforms[super::DEFAULT_PLATFORM],
+	/// Constructs a new `Context` exactly as `new()` does, but additionally registers
+	/// `error_callback` as the context's `pfn_notify` error-notification callback.
+	///
+	/// The OpenCL implementation invokes `error_callback` (possibly from a driver-internal
+	/// thread, at any point during the context's lifetime) whenever an asynchronous error
+	/// occurs that is not tied to a specific command, passing along the driver's `errinfo`
+	/// string and a borrowed view of its `private_info` blob (valid only for the duration
+	/// of the call). This is the only way to observe such
+	/// errors, since enqueue/build calls only ever report synchronous failures.
+	///
+	/// Pass `None` to behave exactly like `new()`.
+	pub fn new_with_callback(platform_idx_opt: Option<usize>, device_types_opt: Option<cl_device_type>,
+			error_callback: Option<ContextErrorCallback>)
+			-> Result<Context, &'static str>
+	{
+		let platform = match Self::resolve_platform(platform_idx_opt) {
+			Ok(platform) => platform,
+			Err(err) => return Err(err),
 		};
-		
+
 		let device_ids: Vec<cl_device_id> = super::get_device_ids(platform, device_types_opt);
 		if device_ids.len() == 0 { return Err("\nNo OpenCL devices found!\n"); }
 
 		// println!("{}OCL::NEW(): device list: {:?}", MT, device_ids);
 
-		let obj: cl_context = super::create_context(&device_ids);
+		// Box the callback a second time so `user_data` is a stable, thin pointer
+		// we can cast back to `&mut ContextErrorCallback` from the trampoline.
+		let mut boxed_callback = error_callback.map(Box::new);
+
+		let (pfn_notify, user_data) = match boxed_callback {
+			Some(ref mut cb) => (
+				Some(context_error_notify as extern "C" fn(*const c_char, *const c_void, size_t, *mut c_void)),
+				&mut **cb as *mut ContextErrorCallback as *mut c_void,
+			),
+			None => (None, ptr::null_mut()),
+		};
+
+		let mut errcode = 0i32;
+
+		let obj: cl_context = unsafe { cl_h::clCreateContext(
+			ptr::null(),
+			device_ids.len() as u32,
+			device_ids.as_ptr(),
+			pfn_notify,
+			user_data,
+			&mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err("\n`clCreateContext` call failed.\n");
+		}
 
 		Ok(Context {
 			platform_opt: Some(platform),
 			device_ids: device_ids,
 			obj: obj,
+			_error_callback: boxed_callback,
+			owned_sub_devices: Vec::new(),
 		})
 	}
 
@@ -120,6 +198,13 @@ impl Context {
 		&self.device_ids
 	}
 
+	/// Returns the devices valid for use in this context wrapped as queryable `Device`s,
+	/// so callers can inspect the hardware a context was built on (name, vendor, compute
+	/// units, memory size, etc.) without a separate enumeration pass.
+	pub fn devices(&self) -> Vec<super::Device> {
+		self.device_ids.iter().cloned().map(super::Device::from_raw).collect()
+	}
+
 	/// Returns the platform our context pertains to.
 	pub fn platform(&self) -> Option<cl_platform_id> {
 		self.platform_opt
@@ -132,10 +217,290 @@ impl Context {
 	}
 
 	/// Releases the current context.
-	pub fn release(&mut self) {		
+	pub fn release(&mut self) {
     	unsafe {
 			cl_h::clReleaseContext(self.obj);
 		}
+
+		// The driver will make no further calls into `pfn_notify` once the
+		// context itself has been released, so it's now safe to drop it.
+		self._error_callback = None;
+
+		// Release any sub-devices (from `Device::partition()`) this context owns.
+		for &sub_device_id in self.owned_sub_devices.iter() {
+			unsafe { cl_h::clReleaseDevice(sub_device_id); }
+		}
+		self.owned_sub_devices.clear();
+	}
+
+	/// Resolves `platform_idx_opt` to a `cl_platform_id` exactly as `new()` does.
+	fn resolve_platform(platform_idx_opt: Option<usize>) -> Result<cl_platform_id, &'static str> {
+		let platforms = super::get_platform_ids();
+		if platforms.len() == 0 { return Err("\nNo OpenCL platforms found!\n"); }
+
+		match platform_idx_opt {
+			Some(pf_idx) => {
+				match platforms.get(pf_idx) {
+					Some(&pf) => Ok(pf),
+					None => Err("Invalid OpenCL platform index specified. \
+						Use 'get_platform_ids()' for a list."),
+				}
+			},
+
+			None => Ok(platforms[super::DEFAULT_PLATFORM]),
+		}
+	}
+
+	/// Constructs a new `Context`, trying `device_types_opt` (or the default device-type
+	/// preference, if `None`) first and automatically retrying with the next type in a
+	/// `GPU -> CPU -> ACCELERATOR -> ALL` preference chain if the requested type has no
+	/// devices available, or if `clCreateContext` itself fails for that type.
+	///
+	/// Returns the `Context` together with the `cl_device_type` that actually succeeded,
+	/// so the caller can adapt things like work-group sizing to whichever kind of device
+	/// it ended up with. This is the common "try GPU context, fall back to CPU" pattern
+	/// that lets a program still run on machines that only expose a CPU OpenCL runtime.
+	///
+	/// # Failures
+	/// - No platforms.
+	/// - Invalid platform index.
+	/// - None of the device types in the preference chain have any devices available.
+	pub fn new_fallback(platform_idx_opt: Option<usize>, device_types_opt: Option<cl_device_type>)
+			-> Result<(Context, cl_device_type), &'static str>
+	{
+		let platform = match Self::resolve_platform(platform_idx_opt) {
+			Ok(platform) => platform,
+			Err(err) => return Err(err),
+		};
+
+		let requested_type = device_types_opt.unwrap_or(cl_h::CL_DEVICE_TYPE_GPU);
+
+		// Try the requested type first, then fall through the remaining preference chain,
+		// skipping a type if it's the one we already just tried.
+		let preference_chain = [cl_h::CL_DEVICE_TYPE_GPU, cl_h::CL_DEVICE_TYPE_CPU,
+			cl_h::CL_DEVICE_TYPE_ACCELERATOR, cl_h::CL_DEVICE_TYPE_ALL];
+
+		let device_types = ::std::iter::once(requested_type)
+			.chain(preference_chain.iter().cloned().filter(|&t| t != requested_type));
+
+		for device_type in device_types {
+			let device_ids: Vec<cl_device_id> = super::get_device_ids(platform, Some(device_type));
+			if device_ids.len() == 0 { continue; }
+
+			let mut errcode = 0i32;
+
+			let obj: cl_context = unsafe { cl_h::clCreateContext(
+				ptr::null(),
+				device_ids.len() as u32,
+				device_ids.as_ptr(),
+				None,
+				ptr::null_mut(),
+				&mut errcode,
+			) };
+
+			if errcode != cl_h::CL_SUCCESS as i32 { continue; }
+
+			return Ok((Context {
+				platform_opt: Some(platform),
+				device_ids: device_ids,
+				obj: obj,
+				_error_callback: None,
+				owned_sub_devices: Vec::new(),
+			}, device_type));
+		}
+
+		Err("\nNo OpenCL devices found for any device type in the fallback preference chain!\n")
+	}
+
+	/// Returns the image formats the given combination of `flags` (e.g.
+	/// `CL_MEM_READ_WRITE`) and `mem_object_type` (e.g. `CL_MEM_OBJECT_IMAGE2D`)
+	/// supports on this context, via `clGetSupportedImageFormats`. Check this before
+	/// creating an `Image` to make sure its `cl_image_format` is actually valid.
+	///
+	/// Returns the `cl_int` error code if either `clGetSupportedImageFormats` call
+	/// fails -- an empty `Vec` means the driver reported zero supported formats, not
+	/// that the query itself failed.
+	pub fn supported_image_formats(&self, flags: cl_h::cl_mem_flags, mem_object_type: cl_h::cl_mem_object_type)
+			-> Result<Vec<cl_h::cl_image_format>, cl_int>
+	{
+		let mut num_formats: cl_h::cl_uint = 0;
+
+		let errcode = unsafe { cl_h::clGetSupportedImageFormats(
+			self.obj, flags, mem_object_type, 0, ptr::null_mut(), &mut num_formats,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as cl_int { return Err(errcode); }
+		if num_formats == 0 { return Ok(Vec::new()); }
+
+		let mut formats: Vec<cl_h::cl_image_format> = Vec::with_capacity(num_formats as usize);
+
+		let errcode = unsafe { cl_h::clGetSupportedImageFormats(
+			self.obj, flags, mem_object_type, num_formats, formats.as_mut_ptr(), ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as cl_int { return Err(errcode); }
+
+		unsafe { formats.set_len(num_formats as usize); }
+
+		Ok(formats)
+	}
+
+	/// Returns a `ContextBuilder` for assembling a `Context` from an explicit list of
+	/// devices (or sub-devices) and an explicit `cl_context_properties` list, rather
+	/// than the all-or-nothing `platform_idx`/`device_types` mask taken by `new()`.
+	///
+	/// This is the route to take when integrating with GL/D3D interop (which requires
+	/// `cl_context_properties` entries such as `CL_GL_CONTEXT_KHR`), when pinning work to
+	/// one hand-picked device, or when building a context over sub-devices returned by
+	/// `Device::partition()`.
+	pub fn builder() -> ContextBuilder {
+		ContextBuilder::new()
+	}
+}
+
+/// A builder for `Context`s constructed from an explicit device list and an explicit
+/// `cl_context_properties` list, via `Context::builder()`.
+pub struct ContextBuilder {
+	platform_opt: Option<cl_platform_id>,
+	device_ids: Vec<cl_device_id>,
+	properties: Vec<cl_context_properties>,
+	error_callback: Option<ContextErrorCallback>,
+	owned_sub_devices: Vec<cl_device_id>,
+}
+
+impl ContextBuilder {
+	fn new() -> ContextBuilder {
+		ContextBuilder {
+			platform_opt: None,
+			device_ids: Vec::new(),
+			properties: Vec::new(),
+			error_callback: None,
+			owned_sub_devices: Vec::new(),
+		}
+	}
+
+	/// Sets the platform recorded on the resulting `Context` (returned later by
+	/// `Context::platform()`). This does not by itself add a `CL_CONTEXT_PLATFORM`
+	/// entry to the properties list -- include that explicitly via `.properties()`
+	/// if the target platform requires it (most do).
+	pub fn platform(mut self, platform: cl_platform_id) -> ContextBuilder {
+		self.platform_opt = Some(platform);
+		self
+	}
+
+	/// Adds a single device (or sub-device) to the list the context will be created over.
+	pub fn device(mut self, device_id: cl_device_id) -> ContextBuilder {
+		self.device_ids.push(device_id);
+		self
+	}
+
+	/// Sets the full list of devices (or sub-devices) the context will be created over,
+	/// replacing any devices added so far.
+	pub fn devices(mut self, device_ids: Vec<cl_device_id>) -> ContextBuilder {
+		self.device_ids = device_ids;
+		self
+	}
+
+	/// Adds sub-devices obtained from `Device::partition()` to the device list the
+	/// context will be created over, and marks them as owned by the resulting
+	/// `Context` so its `release()` also calls `clReleaseDevice` on each of them.
+	pub fn sub_devices(mut self, sub_device_ids: Vec<cl_device_id>) -> ContextBuilder {
+		self.device_ids.extend(sub_device_ids.iter().cloned());
+		self.owned_sub_devices.extend(sub_device_ids);
+		self
+	}
+
+	/// Sets the raw `cl_context_properties` list, e.g.
+	/// `vec![cl_h::CL_CONTEXT_PLATFORM as cl_context_properties, platform as cl_context_properties]`.
+	/// The required trailing `0` terminator is appended automatically by `build()` if
+	/// not already present.
+	pub fn properties(mut self, properties: Vec<cl_context_properties>) -> ContextBuilder {
+		self.properties = properties;
+		self
+	}
+
+	/// Registers an async context error-notification callback, exactly as
+	/// `Context::new_with_callback()` does.
+	pub fn error_callback(mut self, error_callback: ContextErrorCallback) -> ContextBuilder {
+		self.error_callback = Some(error_callback);
+		self
+	}
+
+	/// Consumes the builder and creates the `Context`.
+	///
+	/// # Failures
+	/// - No devices specified (via `.device()` or `.devices()`).
+	/// - `clCreateContext` returns a non-zero error code.
+	pub fn build(self) -> Result<Context, &'static str> {
+		if self.device_ids.is_empty() {
+			return Err("ContextBuilder::build(): No devices specified. \
+				Call `.device()` or `.devices()` before `.build()`.");
+		}
+
+		let properties = terminate_properties(self.properties);
+
+		let mut boxed_callback = self.error_callback.map(Box::new);
+
+		let (pfn_notify, user_data) = match boxed_callback {
+			Some(ref mut cb) => (
+				Some(context_error_notify as extern "C" fn(*const c_char, *const c_void, size_t, *mut c_void)),
+				&mut **cb as *mut ContextErrorCallback as *mut c_void,
+			),
+			None => (None, ptr::null_mut()),
+		};
+
+		let mut errcode = 0i32;
+
+		let obj = unsafe { cl_h::clCreateContext(
+			properties.as_ptr(),
+			self.device_ids.len() as u32,
+			self.device_ids.as_ptr(),
+			pfn_notify,
+			user_data,
+			&mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err("ContextBuilder::build(): `clCreateContext` call failed.");
+		}
+
+		Ok(Context {
+			platform_opt: self.platform_opt,
+			device_ids: self.device_ids,
+			obj: obj,
+			_error_callback: boxed_callback,
+			owned_sub_devices: self.owned_sub_devices,
+		})
+	}
+}
+
+/// Appends the `0` terminator `clCreateContext` requires to `properties`, unless
+/// it's already there. Entries are name/value pairs, so a trailing `0` only counts
+/// as the terminator when it follows a complete pair (a `0` value does not).
+fn terminate_properties(mut properties: Vec<cl_context_properties>) -> Vec<cl_context_properties> {
+	if properties.len() % 2 == 0 || properties.last() != Some(&0) {
+		properties.push(0);
+	}
+	properties
+}
+
+#[cfg(test)]
+mod tests {
+	use super::terminate_properties;
+
+	#[test]
+	fn terminate_properties_appends_missing_terminator() {
+		assert_eq!(terminate_properties(vec![1, 2]), vec![1, 2, 0]);
+	}
+
+	#[test]
+	fn terminate_properties_leaves_existing_terminator_alone() {
+		assert_eq!(terminate_properties(vec![1, 2, 0]), vec![1, 2, 0]);
+	}
+
+	#[test]
+	fn terminate_properties_handles_empty_list() {
+		assert_eq!(terminate_properties(vec![]), vec![0]);
 	}
 }
 
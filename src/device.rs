@@ -0,0 +1,231 @@
+use std::ptr;
+use std::fmt;
+use libc::size_t;
+use cl_h::{self, cl_device_id, cl_device_info, cl_device_type, cl_uint, cl_ulong, cl_int,
+	cl_device_partition_property, cl_device_affinity_domain};
+use super::Platform;
+use super::cl_info;
+
+/// A device-fission scheme, passed to `Device::partition()`.
+///
+/// Corresponds to the `properties` list accepted by `clCreateSubDevices`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PartitionProperty {
+	/// Splits the device into as many sub-devices as will fit evenly, each with
+	/// `compute_units` compute units (`CL_DEVICE_PARTITION_EQUALLY`).
+	Equally(u32),
+	/// Splits the device into one sub-device per entry, where each sub-device gets
+	/// the given number of compute units (`CL_DEVICE_PARTITION_BY_COUNTS`).
+	ByCounts(Vec<u32>),
+	/// Splits the device along the given affinity domain, e.g. NUMA node or shared
+	/// cache level (`CL_DEVICE_PARTITION_BY_AFFINITY_DOMAIN`).
+	ByAffinityDomain(cl_device_affinity_domain),
+}
+
+impl PartitionProperty {
+	/// Builds the raw, zero-terminated `cl_device_partition_property` list that
+	/// `clCreateSubDevices` expects.
+	fn to_raw_properties(&self) -> Vec<cl_device_partition_property> {
+		let mut properties: Vec<cl_device_partition_property> = match *self {
+			PartitionProperty::Equally(compute_units) => vec![
+				cl_h::CL_DEVICE_PARTITION_EQUALLY as cl_device_partition_property,
+				compute_units as cl_device_partition_property,
+			],
+
+			PartitionProperty::ByCounts(ref counts) => {
+				let mut properties = vec![cl_h::CL_DEVICE_PARTITION_BY_COUNTS as cl_device_partition_property];
+				properties.extend(counts.iter().map(|&c| c as cl_device_partition_property));
+				properties.push(cl_h::CL_DEVICE_PARTITION_BY_COUNTS_LIST_END as cl_device_partition_property);
+				properties
+			},
+
+			PartitionProperty::ByAffinityDomain(domain) => vec![
+				cl_h::CL_DEVICE_PARTITION_BY_AFFINITY_DOMAIN as cl_device_partition_property,
+				domain as cl_device_partition_property,
+			],
+		};
+
+		properties.push(0);
+		properties
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PartitionProperty;
+	use cl_h::{self, cl_device_partition_property};
+
+	#[test]
+	fn to_raw_properties_equally() {
+		let raw = PartitionProperty::Equally(4).to_raw_properties();
+		assert_eq!(raw, vec![
+			cl_h::CL_DEVICE_PARTITION_EQUALLY as cl_device_partition_property,
+			4,
+			0,
+		]);
+	}
+
+	#[test]
+	fn to_raw_properties_by_counts() {
+		let raw = PartitionProperty::ByCounts(vec![1, 2, 3]).to_raw_properties();
+		assert_eq!(raw, vec![
+			cl_h::CL_DEVICE_PARTITION_BY_COUNTS as cl_device_partition_property,
+			1, 2, 3,
+			cl_h::CL_DEVICE_PARTITION_BY_COUNTS_LIST_END as cl_device_partition_property,
+			0,
+		]);
+	}
+
+	#[test]
+	fn to_raw_properties_by_affinity_domain() {
+		let raw = PartitionProperty::ByAffinityDomain(cl_h::CL_DEVICE_AFFINITY_DOMAIN_NUMA as u64)
+			.to_raw_properties();
+		assert_eq!(raw, vec![
+			cl_h::CL_DEVICE_PARTITION_BY_AFFINITY_DOMAIN as cl_device_partition_property,
+			cl_h::CL_DEVICE_AFFINITY_DOMAIN_NUMA as cl_device_partition_property,
+			0,
+		]);
+	}
+}
+
+/// A queryable attribute of a `Device`, passed to `Device::info()`.
+///
+/// Corresponds to the `param_name` argument of `clGetDeviceInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceInfo {
+	Name,
+	Vendor,
+	Type,
+	MaxComputeUnits,
+	GlobalMemSize,
+	MaxWorkGroupSize,
+}
+
+impl DeviceInfo {
+	fn to_raw(&self) -> cl_device_info {
+		match *self {
+			DeviceInfo::Name => cl_h::CL_DEVICE_NAME,
+			DeviceInfo::Vendor => cl_h::CL_DEVICE_VENDOR,
+			DeviceInfo::Type => cl_h::CL_DEVICE_TYPE,
+			DeviceInfo::MaxComputeUnits => cl_h::CL_DEVICE_MAX_COMPUTE_UNITS,
+			DeviceInfo::GlobalMemSize => cl_h::CL_DEVICE_GLOBAL_MEM_SIZE,
+			DeviceInfo::MaxWorkGroupSize => cl_h::CL_DEVICE_MAX_WORK_GROUP_SIZE,
+		}
+	}
+}
+
+/// The value returned by `Device::info()`, typed according to which `DeviceInfo`
+/// variant was queried.
+#[derive(Clone, Debug)]
+pub enum DeviceInfoResult {
+	Str(String),
+	DeviceType(cl_device_type),
+	U32(cl_uint),
+	U64(cl_ulong),
+	Size(size_t),
+}
+
+impl fmt::Display for DeviceInfoResult {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DeviceInfoResult::Str(ref s) => write!(f, "{}", s),
+			DeviceInfoResult::DeviceType(t) => write!(f, "{}", t),
+			DeviceInfoResult::U32(n) => write!(f, "{}", n),
+			DeviceInfoResult::U64(n) => write!(f, "{}", n),
+			DeviceInfoResult::Size(n) => write!(f, "{}", n),
+		}
+	}
+}
+
+/// A thin wrapper around a `cl_device_id` which can be queried for its name,
+/// vendor, type, and capacity (compute units / global memory / work-group size).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Device {
+	id: cl_device_id,
+}
+
+impl Device {
+	/// Returns every device of any type available on `platform`.
+	pub fn list_all(platform: &Platform) -> Vec<Device> {
+		super::get_device_ids(platform.id(), Some(cl_h::CL_DEVICE_TYPE_ALL))
+			.into_iter().map(Device::from_raw).collect()
+	}
+
+	/// Wraps an existing `cl_device_id`, such as one obtained from `Context::device_ids()`.
+	pub fn from_raw(id: cl_device_id) -> Device {
+		Device { id: id }
+	}
+
+	/// Returns the wrapped `cl_device_id`.
+	pub fn id(&self) -> cl_device_id {
+		self.id
+	}
+
+	/// Queries `clGetDeviceInfo` for `info_kind` and returns the appropriately typed
+	/// result, or the `cl_int` error code if the query itself failed -- callers that
+	/// make decisions based on the result (e.g. picking the device with the most
+	/// compute units) should not treat a failed query as a legitimate `0`.
+	pub fn info(&self, info_kind: DeviceInfo) -> Result<DeviceInfoResult, cl_int> {
+		match info_kind {
+			DeviceInfo::Name | DeviceInfo::Vendor =>
+				self.info_string(info_kind).map(DeviceInfoResult::Str),
+			DeviceInfo::Type =>
+				self.info_fixed::<cl_device_type>(info_kind).map(DeviceInfoResult::DeviceType),
+			DeviceInfo::MaxComputeUnits =>
+				self.info_fixed::<cl_uint>(info_kind).map(DeviceInfoResult::U32),
+			DeviceInfo::GlobalMemSize =>
+				self.info_fixed::<cl_ulong>(info_kind).map(DeviceInfoResult::U64),
+			DeviceInfo::MaxWorkGroupSize =>
+				self.info_fixed::<size_t>(info_kind).map(DeviceInfoResult::Size),
+		}
+	}
+
+	fn info_string(&self, info_kind: DeviceInfo) -> Result<String, cl_int> {
+		cl_info::query_string(|size, value, size_ret| unsafe {
+			cl_h::clGetDeviceInfo(self.id, info_kind.to_raw(), size, value, size_ret)
+		})
+	}
+
+	fn info_fixed<T: Default>(&self, info_kind: DeviceInfo) -> Result<T, cl_int> {
+		cl_info::query_fixed(|size, value| unsafe {
+			cl_h::clGetDeviceInfo(self.id, info_kind.to_raw(), size, value, ptr::null_mut())
+		})
+	}
+
+	/// Splits this device into sub-devices according to `property` (device fission),
+	/// e.g. to divide a multi-core CPU or a large GPU so that independent workloads
+	/// can each get their own `Context` restricted to a subset of compute units.
+	///
+	/// The returned sub-device ids are retained by the driver and must eventually be
+	/// released with `clReleaseDevice`; feed them to `Context::builder().sub_devices(..)`
+	/// so the resulting `Context` releases them automatically.
+	///
+	/// # Failures
+	/// - `clCreateSubDevices` returns a non-zero error code, e.g. because `property`
+	///   isn't supported by this device or asks for more compute units than it has.
+	pub fn partition(&self, property: PartitionProperty) -> Result<Vec<cl_device_id>, &'static str> {
+		let properties = property.to_raw_properties();
+
+		let mut num_devices: cl_uint = 0;
+
+		let errcode = unsafe { cl_h::clCreateSubDevices(
+			self.id, properties.as_ptr(), 0, ptr::null_mut(), &mut num_devices,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err("Device::partition(): `clCreateSubDevices` call failed (querying count).");
+		}
+
+		let mut sub_device_ids: Vec<cl_device_id> = vec![ptr::null_mut(); num_devices as usize];
+
+		let errcode = unsafe { cl_h::clCreateSubDevices(
+			self.id, properties.as_ptr(), num_devices, sub_device_ids.as_mut_ptr(), ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err("Device::partition(): `clCreateSubDevices` call failed (creating sub-devices).");
+		}
+
+		Ok(sub_device_ids)
+	}
+}
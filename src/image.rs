@@ -0,0 +1,226 @@
+use std::ptr;
+use libc::{c_void, size_t};
+use cl_h::{self, cl_mem, cl_mem_flags, cl_command_queue, cl_image_format};
+
+/// A 2D or 3D OpenCL image memory object, the sampled-image counterpart to the flat,
+/// buffer-backed `Envoy`.
+///
+/// Wraps a `cl_mem` such as that returned by `clCreateImage2D`/`clCreateImage3D`, along
+/// with the `cl_command_queue` used by its `read()`/`write()` helpers.
+///
+/// # Incomplete: not yet usable as a kernel argument
+/// `Kernel` (like `Envoy`) is not part of this chunk of the source tree, so there is no
+/// `Kernel::arg_img()` to bind an `Image` with -- the only way to get data into or out
+/// of one is `read()`/`write()`. Treat image support as half-landed until that method
+/// exists; see the note on `obj()` below for what it should do.
+pub struct Image {
+	obj: cl_mem,
+	queue: cl_command_queue,
+	format: cl_image_format,
+	width: usize,
+	height: usize,
+	depth: usize,
+}
+
+impl Image {
+	/// Creates a 2D image of `width` x `height` pixels in `format`, readable/writable
+	/// through `queue`. Pass `0` for `row_pitch` to let the driver choose it.
+	pub fn create_2d(context: cl_h::cl_context, queue: cl_command_queue, flags: cl_mem_flags,
+			format: cl_image_format, width: usize, height: usize, row_pitch: usize)
+			-> Result<Image, String>
+	{
+		let mut errcode = 0i32;
+
+		let obj = unsafe { cl_h::clCreateImage2D(
+			context, flags, &format, width as size_t, height as size_t,
+			row_pitch as size_t, ptr::null_mut(), &mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err(format!("Image::create_2d(): `clCreateImage2D` failed (errcode: {})", errcode));
+		}
+
+		Ok(Image { obj: obj, queue: queue, format: format, width: width, height: height, depth: 1 })
+	}
+
+	/// Creates a 3D image of `width` x `height` x `depth` voxels in `format`,
+	/// readable/writable through `queue`. Pass `0` for either pitch to let the
+	/// driver choose it.
+	pub fn create_3d(context: cl_h::cl_context, queue: cl_command_queue, flags: cl_mem_flags,
+			format: cl_image_format, width: usize, height: usize, depth: usize,
+			row_pitch: usize, slice_pitch: usize)
+			-> Result<Image, String>
+	{
+		let mut errcode = 0i32;
+
+		let obj = unsafe { cl_h::clCreateImage3D(
+			context, flags, &format, width as size_t, height as size_t, depth as size_t,
+			row_pitch as size_t, slice_pitch as size_t, ptr::null_mut(), &mut errcode,
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err(format!("Image::create_3d(): `clCreateImage3D` failed (errcode: {})", errcode));
+		}
+
+		Ok(Image { obj: obj, queue: queue, format: format, width: width, height: height, depth: depth })
+	}
+
+	/// The exact byte size of the tightly-packed buffer `write()`/`read()` expect:
+	/// `width * height * depth * bytes_per_pixel(format)`.
+	pub fn byte_len(&self) -> usize {
+		self.width * self.height * self.depth * bytes_per_pixel(&self.format)
+	}
+
+	/// Blocking write of `data` (tightly packed, no padding) to the entire image.
+	///
+	/// # Failures
+	/// - `data.len()` does not equal `self.byte_len()`.
+	pub fn write(&self, data: &[u8]) -> Result<(), String> {
+		if data.len() != self.byte_len() {
+			return Err(format!("Image::write(): data is {} bytes, expected {} ({}x{}x{} pixels).",
+				data.len(), self.byte_len(), self.width, self.height, self.depth));
+		}
+
+		let origin = [0 as size_t, 0, 0];
+		let region = [self.width as size_t, self.height as size_t, self.depth as size_t];
+
+		let errcode = unsafe { cl_h::clEnqueueWriteImage(
+			self.queue, self.obj, cl_h::CL_TRUE, origin.as_ptr(), region.as_ptr(),
+			0, 0, data.as_ptr() as *const c_void, 0, ptr::null(), ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err(format!("Image::write(): `clEnqueueWriteImage` failed (errcode: {})", errcode));
+		}
+
+		Ok(())
+	}
+
+	/// Blocking read of the entire image into `data` (tightly packed, no padding).
+	///
+	/// # Failures
+	/// - `data.len()` does not equal `self.byte_len()`.
+	pub fn read(&self, data: &mut [u8]) -> Result<(), String> {
+		if data.len() != self.byte_len() {
+			return Err(format!("Image::read(): data is {} bytes, expected {} ({}x{}x{} pixels).",
+				data.len(), self.byte_len(), self.width, self.height, self.depth));
+		}
+
+		let origin = [0 as size_t, 0, 0];
+		let region = [self.width as size_t, self.height as size_t, self.depth as size_t];
+
+		let errcode = unsafe { cl_h::clEnqueueReadImage(
+			self.queue, self.obj, cl_h::CL_TRUE, origin.as_ptr(), region.as_ptr(),
+			0, 0, data.as_mut_ptr() as *mut c_void, 0, ptr::null(), ptr::null_mut(),
+		) };
+
+		if errcode != cl_h::CL_SUCCESS as i32 {
+			return Err(format!("Image::read(): `clEnqueueReadImage` failed (errcode: {})", errcode));
+		}
+
+		Ok(())
+	}
+
+	/// Returns the image as a `*mut libc::c_void`, for binding as a kernel argument.
+	///
+	/// NOTE: there is no `Kernel::arg_img()` yet to bind this with -- `Kernel` (like
+	/// `Envoy`) is not part of this chunk of the source tree, so that half of this
+	/// request could not be wired up here. `arg_img(&self, image: &Image)` should
+	/// mirror `arg_env()` and set this value as a `CL_MEM` argument once `Kernel`
+	/// lands.
+	pub fn obj(&self) -> cl_mem {
+		self.obj
+	}
+
+	pub fn format(&self) -> cl_image_format {
+		self.format
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+
+	/// Releases the underlying `cl_mem`.
+	pub fn release(&mut self) {
+		unsafe { cl_h::clReleaseMemObject(self.obj); }
+	}
+}
+
+/// The number of bytes one pixel of `format` occupies, i.e. `num_channels(format)
+/// * bytes_per_channel(format)` -- except for the packed formats, which pack all of
+/// their channels into a single machine word regardless of channel count.
+fn bytes_per_pixel(format: &cl_image_format) -> usize {
+	match format.image_channel_data_type {
+		cl_h::CL_UNORM_SHORT_565 | cl_h::CL_UNORM_SHORT_555 => return 2,
+		cl_h::CL_UNORM_INT_101010 => return 4,
+		_ => {},
+	}
+
+	let num_channels = match format.image_channel_order {
+		cl_h::CL_R | cl_h::CL_A | cl_h::CL_INTENSITY | cl_h::CL_LUMINANCE | cl_h::CL_Rx => 1,
+		cl_h::CL_RG | cl_h::CL_RA | cl_h::CL_RGx => 2,
+		cl_h::CL_RGB | cl_h::CL_RGBx => 3,
+		cl_h::CL_RGBA | cl_h::CL_BGRA | cl_h::CL_ARGB => 4,
+		_ => 4,
+	};
+
+	let bytes_per_channel = match format.image_channel_data_type {
+		cl_h::CL_SNORM_INT8 | cl_h::CL_UNORM_INT8 |
+			cl_h::CL_SIGNED_INT8 | cl_h::CL_UNSIGNED_INT8 => 1,
+		cl_h::CL_SNORM_INT16 | cl_h::CL_UNORM_INT16 |
+			cl_h::CL_SIGNED_INT16 | cl_h::CL_UNSIGNED_INT16 | cl_h::CL_HALF_FLOAT => 2,
+		cl_h::CL_SIGNED_INT32 | cl_h::CL_UNSIGNED_INT32 | cl_h::CL_FLOAT => 4,
+		_ => 4,
+	};
+
+	num_channels * bytes_per_channel
+}
+
+#[cfg(test)]
+mod tests {
+	use super::bytes_per_pixel;
+	use cl_h;
+
+	fn format(order: cl_h::cl_channel_order, data_type: cl_h::cl_channel_type) -> cl_h::cl_image_format {
+		cl_h::cl_image_format {
+			image_channel_order: order,
+			image_channel_data_type: data_type,
+		}
+	}
+
+	#[test]
+	fn packed_formats_ignore_channel_order() {
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_RGB, cl_h::CL_UNORM_SHORT_565)), 2);
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_RGBA, cl_h::CL_UNORM_SHORT_555)), 2);
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_RGB, cl_h::CL_UNORM_INT_101010)), 4);
+	}
+
+	#[test]
+	fn single_channel_byte() {
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_R, cl_h::CL_UNORM_INT8)), 1);
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_LUMINANCE, cl_h::CL_SIGNED_INT8)), 1);
+	}
+
+	#[test]
+	fn rgba_float() {
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_RGBA, cl_h::CL_FLOAT)), 16);
+	}
+
+	#[test]
+	fn rgb_half_float() {
+		assert_eq!(bytes_per_pixel(&format(cl_h::CL_RGB, cl_h::CL_HALF_FLOAT)), 6);
+	}
+
+	#[test]
+	fn unrecognized_order_and_type_default_to_four_channels_of_four_bytes() {
+		assert_eq!(bytes_per_pixel(&format(0xFFFF, 0xFFFF)), 16);
+	}
+}
@@ -0,0 +1,64 @@
+use cl_h::{self, cl_platform_id, cl_platform_info, cl_int};
+use super::cl_info;
+
+/// A queryable attribute of a `Platform`, passed to `Platform::info()`.
+///
+/// Corresponds to the `param_name` argument of `clGetPlatformInfo`. All platform
+/// attributes are returned by the driver as strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlatformInfo {
+	Profile,
+	Version,
+	Name,
+	Vendor,
+	Extensions,
+}
+
+impl PlatformInfo {
+	fn to_raw(&self) -> cl_platform_info {
+		match *self {
+			PlatformInfo::Profile => cl_h::CL_PLATFORM_PROFILE,
+			PlatformInfo::Version => cl_h::CL_PLATFORM_VERSION,
+			PlatformInfo::Name => cl_h::CL_PLATFORM_NAME,
+			PlatformInfo::Vendor => cl_h::CL_PLATFORM_VENDOR,
+			PlatformInfo::Extensions => cl_h::CL_PLATFORM_EXTENSIONS,
+		}
+	}
+}
+
+/// A thin wrapper around a `cl_platform_id` which can be queried for its
+/// name, vendor, version, profile and supported extensions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Platform {
+	id: cl_platform_id,
+}
+
+impl Platform {
+	/// Returns every platform visible to the OpenCL ICD loader.
+	pub fn list() -> Vec<Platform> {
+		super::get_platform_ids().into_iter().map(Platform::from_raw).collect()
+	}
+
+	/// Wraps an existing `cl_platform_id`, such as one obtained from `Context::platform()`.
+	pub fn from_raw(id: cl_platform_id) -> Platform {
+		Platform { id: id }
+	}
+
+	/// Returns the wrapped `cl_platform_id`.
+	pub fn id(&self) -> cl_platform_id {
+		self.id
+	}
+
+	/// Returns every device available on this platform.
+	pub fn devices(&self) -> Vec<super::Device> {
+		super::Device::list_all(self)
+	}
+
+	/// Queries `clGetPlatformInfo` for `info_kind` and returns the result as an owned
+	/// `String`, or the `cl_int` error code if the query itself failed.
+	pub fn info(&self, info_kind: PlatformInfo) -> Result<String, cl_int> {
+		cl_info::query_string(|size, value, size_ret| unsafe {
+			cl_h::clGetPlatformInfo(self.id, info_kind.to_raw(), size, value, size_ret)
+		})
+	}
+}